@@ -0,0 +1,4 @@
+pub mod types;
+pub mod memory;
+pub mod mmu;
+pub mod mmio;
@@ -1,31 +1,66 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::Range;
 use crate::types::{MemoryOperationSize, Register};
+use crate::mmu::{AccessKind, AddressingMode, Mmu, PageFlags};
+use crate::mmio::MmioBus;
 
 
+// byte order used when reading/writing multi-byte values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+// the kind of fault a memory access failed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryFaultKind {
+    // the access (or part of it) fell outside of raw_memory
+    OutOfBounds,
+    // the access touched a byte that was never written
+    Uninitialized,
+    // the virtual address has no mapping in the page table
+    PageFault,
+    // the page is mapped, but not for the kind of access attempted
+    ProtectionViolation,
+}
+
+// describes why a memory access failed, so callers can translate
+// it into a trap instead of the VM panicking
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryFault {
+    pub address: usize,
+    pub size: MemoryOperationSize,
+    pub kind: MemoryFaultKind,
+}
+
 /**
  * ManageMemory Trait
- * 
+ *
  * Contains definitions to read and write memory in
  * either 1-, 2- or 4-byte chunks.
- * 
- * ToDo: Add 4 byte operations
  */
 pub trait ManageMemory {
     // Read operations
-    // will call either "read8" or  "read16" on size parameter
-    fn read(&self, position : usize, size : MemoryOperationSize) -> i32;
+    // will call either "read8", "read16" or "read32" on size parameter
+    fn read(&self, position : usize, size : MemoryOperationSize) -> Result<i32, MemoryFault>;
     // reads 1 byte from the memory
-    fn read8(&self, position : usize) -> i8;
+    fn read8(&self, position : usize) -> Result<i8, MemoryFault>;
     // reads 2 bytes from the memory
-    fn read16(&self, position : usize) -> i16;
+    fn read16(&self, position : usize) -> Result<i16, MemoryFault>;
+    // reads 4 bytes from the memory
+    fn read32(&self, position : usize) -> Result<i32, MemoryFault>;
 
     // Write operations
-    // will call either "write8" or  "write16" on size parameter
-    fn write(&mut self, position : usize, value : i32, size : MemoryOperationSize);
+    // will call either "write8", "write16" or "write32" on size parameter
+    fn write(&mut self, position : usize, value : i32, size : MemoryOperationSize) -> Result<(), MemoryFault>;
     // reads 1 byte from the memory
-    fn write8(&mut self, position : usize, value : i8);
+    fn write8(&mut self, position : usize, value : i8) -> Result<(), MemoryFault>;
     // reads 2 bytes from the memory
-    fn write16(&mut self, position : usize, value : i16);
+    fn write16(&mut self, position : usize, value : i16) -> Result<(), MemoryFault>;
+    // writes 4 bytes to the memory
+    fn write32(&mut self, position : usize, value : i32) -> Result<(), MemoryFault>;
 }
 
 pub trait ManageRegisters {
@@ -37,29 +72,81 @@ pub trait ManageRegisters {
 
 /**
  * ManageHeap Trait
- * 
+ *
  * Contains definition to manage the heap memory.
  * Allocates or de-allocates specific ranges of memory for
  * programs or data within those programs.
  */
 pub trait ManageHeap {
-    // Finds available heap location with enough space
-    fn find_available_heap(size: u32) -> usize;
+    // Finds available heap location with enough space,
+    // without allocating it. Returns the position data would
+    // start at if allocated, or None if the heap is exhausted.
+    fn find_available_heap(&self, size: u32) -> Option<usize>;
 
     // Allocates heap memory with a specified amount of
-    // bytes at the specified position.
-    fn allocate_heap(size: u32) -> usize;
+    // bytes and returns the position it was allocated at,
+    // or None if there is no free block big enough.
+    fn allocate_heap(&mut self, size: u32) -> Option<usize>;
 
-    // Frees the heap at a specified position
-    fn free_heap(position : usize);
+    // Frees the heap at a specified position, returning it to the
+    // free list so it can be reused. Returns None without touching
+    // anything if `position` isn't the start of a live allocation
+    // (a bogus pointer, or one that was already freed).
+    fn free_heap(&mut self, position : usize) -> Option<()>;
 }
 
+// size in bytes of the header stored right before every heap
+// allocation, recording how many bytes the allocation holds
+const HEAP_HEADER_SIZE: usize = 2;
+
+// largest allocation accepted through the heap API; the header
+// itself is a raw u16 and could record any length up to u16::MAX,
+// but this keeps allocation sizes within the same i16 range as the
+// rest of the VM's word-sized values
+const HEAP_MAX_ALLOCATION_SIZE: u32 = i16::MAX as u32;
+
+// offset at which the heap region starts, leaving the low
+// addresses of raw_memory free for the register file
+const HEAP_START: usize = 64;
+
 pub struct Memory {
     // internal array / vector containing the complete memory
     raw_memory:  Vec<i8>,
 
     // hash map containing memory locations for each register
     register_lookup_table: HashMap<i32, i32>,
+
+    // list of (start, size) free blocks within the heap region,
+    // kept sorted by start address
+    heap_free_list: Vec<(usize, usize)>,
+
+    // block_start of every allocation currently handed out by
+    // allocate_heap and not yet freed; lets free_heap reject a
+    // bogus or already-freed position instead of trusting
+    // whatever bytes happen to sit before it as a header
+    heap_allocations: HashSet<usize>,
+
+    // byte order used for multi-byte reads and writes
+    endianness: Endianness,
+
+    // tracks which bytes of raw_memory have been written to;
+    // reading a byte that is still false is a bug in the guest
+    // program, not a valid zero value
+    initialized: Vec<bool>,
+
+    // page table used to translate virtual addresses when
+    // addressing_mode is AddressingMode::Paged
+    mmu: Mmu,
+
+    // whether accesses treat `position` as a physical offset
+    // directly (Bare) or as a virtual address to translate
+    // through the mmu (Paged)
+    addressing_mode: AddressingMode,
+
+    // memory-mapped I/O regions; an access whose physical address
+    // falls inside one of these is routed to the device instead
+    // of raw_memory
+    mmio: MmioBus,
 }
 
 impl Memory {
@@ -93,70 +180,427 @@ impl Memory {
 
 
     // initializes the internal state of the memory implementation
-    fn init() -> Memory {
+    fn init(endianness : Endianness) -> Memory {
         let mut result = Memory {
             raw_memory: Vec::new(),
-            register_lookup_table: HashMap::new()
+            register_lookup_table: HashMap::new(),
+            heap_free_list: Vec::new(),
+            heap_allocations: HashSet::new(),
+            endianness: endianness,
+            initialized: Vec::new(),
+            mmu: Mmu::new(),
+            addressing_mode: AddressingMode::Bare,
+            mmio: MmioBus::new(),
         };
 
         Memory::init_register_lookup_table(&mut result);
 
         // init memory
-        for _index in 0..4096  { 
+        for _index in 0..4096  {
             result.raw_memory.push(0);
+            result.initialized.push(false);
         }
 
+        // the register file occupies 0..HEAP_START and starts out
+        // zeroed, so it reads back as initialized from the start
+        result.mark_initialized(0..HEAP_START);
+
+        // the whole heap region starts out as a single free block
+        let heap_size = result.raw_memory.len() - HEAP_START;
+        result.heap_free_list.push((HEAP_START, heap_size));
+
         return result;
     }
 
-    // returns an initialized memory struct
+    // returns an initialized memory struct, defaulting to the
+    // big-endian byte order the VM has always used
     pub fn new() -> Memory {
-        Memory::init()
+        Memory::init(Endianness::Big)
+    }
+
+    // returns an initialized memory struct using the given byte
+    // order for multi-byte reads and writes
+    pub fn with_endianness(endianness : Endianness) -> Memory {
+        Memory::init(endianness)
+    }
+
+    // changes the byte order used for multi-byte reads and writes
+    pub fn set_endianness(&mut self, endianness : Endianness) {
+        self.endianness = endianness;
+    }
+
+    // switches between bare (identity-mapped) and paged addressing
+    pub fn set_addressing_mode(&mut self, addressing_mode : AddressingMode) {
+        self.addressing_mode = addressing_mode;
+    }
+
+    // maps the page containing `vaddr` to the page containing
+    // `paddr` with the given permissions
+    pub fn map_page(&mut self, vaddr : usize, paddr : usize, flags : PageFlags) {
+        self.mmu.map_page(vaddr, paddr, flags);
+    }
+
+    // removes the mapping for the page containing `vaddr`
+    pub fn unmap_page(&mut self, vaddr : usize) {
+        self.mmu.unmap_page(vaddr);
+    }
+
+    // registers a device over the physical address range
+    // `[start, end)`, routing accesses there to it instead of
+    // raw_memory
+    pub fn map_device(&mut self, start : usize, end : usize, device : Box<dyn crate::mmio::MemoryDevice>) {
+        self.mmio.map(start, end, device);
+    }
+
+    // removes the device mapped at `start`
+    pub fn unmap_device(&mut self, start : usize) {
+        self.mmio.unmap(start);
+    }
+
+    // advances every mapped device (e.g. the timer) by one tick
+    pub fn tick(&mut self) {
+        self.mmio.tick();
+    }
+}
+
+impl Memory {
+    // resolves a `len`-byte access at `position` to a physical
+    // offset: unchanged when addressing is Bare, translated page
+    // by page through the mmu (and checked against each page's
+    // permissions) when addressing is Paged
+    fn translate(&self, position : usize, len : usize, access : AccessKind, size : MemoryOperationSize) -> Result<usize, MemoryFault> {
+        match self.addressing_mode {
+            AddressingMode::Bare => Ok(position),
+            AddressingMode::Paged => self.mmu.translate(position, len, access, size),
+        }
+    }
+
+    // returns an OutOfBounds fault unless every byte in
+    // `position .. position + size` lies within raw_memory
+    fn check_bounds(&self, position : usize, size : usize, op_size : MemoryOperationSize) -> Result<(), MemoryFault> {
+        if position.checked_add(size).map_or(true, |end| end > self.raw_memory.len()) {
+            return Err(MemoryFault {
+                address: position,
+                size: op_size,
+                kind: MemoryFaultKind::OutOfBounds,
+            });
+        }
+
+        return Ok(());
+    }
+
+    // returns an Uninitialized fault unless every byte in
+    // `position .. position + size` has been written to before
+    fn check_initialized(&self, position : usize, size : usize, op_size : MemoryOperationSize) -> Result<(), MemoryFault> {
+        if self.initialized[position..position + size].iter().any(|&byte| !byte) {
+            return Err(MemoryFault {
+                address: position,
+                size: op_size,
+                kind: MemoryFaultKind::Uninitialized,
+            });
+        }
+
+        return Ok(());
+    }
+
+    // marks every byte in `range` as having been written to
+    pub fn mark_initialized(&mut self, range : Range<usize>) {
+        for byte in self.initialized[range].iter_mut() {
+            *byte = true;
+        }
+    }
+
+    // marks every byte in `range` as not having been written to,
+    // e.g. after a heap block is freed
+    pub fn mark_uninitialized(&mut self, range : Range<usize>) {
+        for byte in self.initialized[range].iter_mut() {
+            *byte = false;
+        }
+    }
+
+    // stores a heap allocation's length directly as the raw header
+    // bytes at `block_start`, in the configured byte order; bypasses
+    // write16 so the header round-trips correctly regardless of
+    // whether the low byte has its high bit set (read16/write16 only
+    // ever carry signed i16 payloads, which isn't what the header is)
+    fn write_heap_header(&mut self, block_start : usize, size : u16) {
+        let bytes = match self.endianness {
+            Endianness::Big => size.to_be_bytes(),
+            Endianness::Little => size.to_le_bytes(),
+        };
+
+        self.raw_memory[block_start] = bytes[0] as i8;
+        self.raw_memory[block_start + 1] = bytes[1] as i8;
+        self.mark_initialized(block_start..block_start + HEAP_HEADER_SIZE);
+    }
+
+    // reads a heap allocation's length back from the raw header bytes
+    // at `block_start`; the counterpart to `write_heap_header`
+    fn read_heap_header(&self, block_start : usize) -> u16 {
+        let bytes = [
+            self.raw_memory[block_start] as u8,
+            self.raw_memory[block_start + 1] as u8,
+        ];
+
+        match self.endianness {
+            Endianness::Big => u16::from_be_bytes(bytes),
+            Endianness::Little => u16::from_le_bytes(bytes),
+        }
     }
 }
 
-// ToDo: Check out of bounds when reading and writing
-// ToDo: Implement 4 byte operations
 impl ManageMemory for Memory {
-    fn read(&self, position : usize, size : MemoryOperationSize) -> i32 {
+    fn read(&self, position : usize, size : MemoryOperationSize) -> Result<i32, MemoryFault> {
         match size {
-            MemoryOperationSize::Byte => return self.read8(position) as i32,
-            MemoryOperationSize::Word => return self.read16(position) as i32,
+            MemoryOperationSize::Byte => return self.read8(position).map(|value| value as i32),
+            MemoryOperationSize::Word => return self.read16(position).map(|value| value as i32),
+            MemoryOperationSize::Dword => return self.read32(position),
         };
     }
 
-    fn read8(&self, position : usize) -> i8 {
-        return self.raw_memory[position];
+    fn read8(&self, position : usize) -> Result<i8, MemoryFault> {
+        let position = self.translate(position, 1, AccessKind::Read, MemoryOperationSize::Byte)?;
+
+        if let Some(value) = self.mmio.read(position, MemoryOperationSize::Byte) {
+            return Ok(value as i8);
+        }
+
+        self.check_bounds(position, 1, MemoryOperationSize::Byte)?;
+        self.check_initialized(position, 1, MemoryOperationSize::Byte)?;
+
+        return Ok(self.raw_memory[position]);
+    }
+
+    fn read16(&self, position : usize) -> Result<i16, MemoryFault> {
+        let position = self.translate(position, 2, AccessKind::Read, MemoryOperationSize::Word)?;
+
+        if let Some(value) = self.mmio.read(position, MemoryOperationSize::Word) {
+            return Ok(value as i16);
+        }
+
+        self.check_bounds(position, 2, MemoryOperationSize::Word)?;
+        self.check_initialized(position, 2, MemoryOperationSize::Word)?;
+
+        let bytes = [
+            self.raw_memory[position] as u8,
+            self.raw_memory[position + 1] as u8,
+        ];
+
+        return Ok(match self.endianness {
+            Endianness::Big => i16::from_be_bytes(bytes),
+            Endianness::Little => i16::from_le_bytes(bytes),
+        });
     }
 
-    fn read16(&self, position : usize) -> i16 {
-        return (i16::from(self.raw_memory[position]) << 8) + i16::from(self.raw_memory[position + 1]);
+    fn read32(&self, position : usize) -> Result<i32, MemoryFault> {
+        let position = self.translate(position, 4, AccessKind::Read, MemoryOperationSize::Dword)?;
+
+        if let Some(value) = self.mmio.read(position, MemoryOperationSize::Dword) {
+            return Ok(value);
+        }
+
+        self.check_bounds(position, 4, MemoryOperationSize::Dword)?;
+        self.check_initialized(position, 4, MemoryOperationSize::Dword)?;
+
+        let bytes = [
+            self.raw_memory[position] as u8,
+            self.raw_memory[position + 1] as u8,
+            self.raw_memory[position + 2] as u8,
+            self.raw_memory[position + 3] as u8,
+        ];
+
+        return Ok(match self.endianness {
+            Endianness::Big => i32::from_be_bytes(bytes),
+            Endianness::Little => i32::from_le_bytes(bytes),
+        });
     }
 
-    fn write(&mut self, position : usize, value : i32, size : MemoryOperationSize) {
+    fn write(&mut self, position : usize, value : i32, size : MemoryOperationSize) -> Result<(), MemoryFault> {
         match size {
             MemoryOperationSize::Byte => self.write8(position, value as i8),
             MemoryOperationSize::Word => self.write16(position, value as i16),
-        };
+            MemoryOperationSize::Dword => self.write32(position, value),
+        }
     }
 
-    fn write8(&mut self, position : usize, value : i8) {
+    fn write8(&mut self, position : usize, value : i8) -> Result<(), MemoryFault> {
+        let position = self.translate(position, 1, AccessKind::Write, MemoryOperationSize::Byte)?;
+
+        if self.mmio.write(position, value as i32, MemoryOperationSize::Byte) {
+            return Ok(());
+        }
+
+        self.check_bounds(position, 1, MemoryOperationSize::Byte)?;
+
         self.raw_memory[position] = value;
+        self.mark_initialized(position..position + 1);
+        return Ok(());
     }
 
-    fn write16(&mut self, position : usize, value : i16) {
-        self.raw_memory[position] = (value >> 8) as i8;
-        self.raw_memory[position+1] = value as i8;
+    fn write16(&mut self, position : usize, value : i16) -> Result<(), MemoryFault> {
+        let position = self.translate(position, 2, AccessKind::Write, MemoryOperationSize::Word)?;
+
+        if self.mmio.write(position, value as i32, MemoryOperationSize::Word) {
+            return Ok(());
+        }
+
+        self.check_bounds(position, 2, MemoryOperationSize::Word)?;
+
+        let high_byte = (value >> 8) as i8;
+        let low_byte = value as i8;
+
+        match self.endianness {
+            Endianness::Big => {
+                self.raw_memory[position] = high_byte;
+                self.raw_memory[position+1] = low_byte;
+            },
+            Endianness::Little => {
+                self.raw_memory[position] = low_byte;
+                self.raw_memory[position+1] = high_byte;
+            },
+        };
+
+        self.mark_initialized(position..position + 2);
+        return Ok(());
+    }
+
+    fn write32(&mut self, position : usize, value : i32) -> Result<(), MemoryFault> {
+        let position = self.translate(position, 4, AccessKind::Write, MemoryOperationSize::Dword)?;
+
+        if self.mmio.write(position, value, MemoryOperationSize::Dword) {
+            return Ok(());
+        }
+
+        self.check_bounds(position, 4, MemoryOperationSize::Dword)?;
+
+        let bytes = match self.endianness {
+            Endianness::Big => value.to_be_bytes(),
+            Endianness::Little => value.to_le_bytes(),
+        };
+
+        self.raw_memory[position] = bytes[0] as i8;
+        self.raw_memory[position + 1] = bytes[1] as i8;
+        self.raw_memory[position + 2] = bytes[2] as i8;
+        self.raw_memory[position + 3] = bytes[3] as i8;
+
+        self.mark_initialized(position..position + 4);
+        return Ok(());
     }
 } // impl ManageMemory for Memory
 
 
+impl ManageHeap for Memory {
+    fn find_available_heap(&self, size : u32) -> Option<usize> {
+        if size > HEAP_MAX_ALLOCATION_SIZE {
+            return None;
+        }
+
+        let needed = size as usize + HEAP_HEADER_SIZE;
+
+        for &(start, block_size) in self.heap_free_list.iter() {
+            if block_size >= needed {
+                return Some(start + HEAP_HEADER_SIZE);
+            }
+        }
+
+        return None;
+    }
+
+    fn allocate_heap(&mut self, size : u32) -> Option<usize> {
+        // find_available_heap is the read-only probe for "is there a
+        // free block big enough"; reuse it here instead of scanning
+        // heap_free_list a second time
+        let data_start = self.find_available_heap(size)?;
+        let block_start = data_start - HEAP_HEADER_SIZE;
+        let needed = size as usize + HEAP_HEADER_SIZE;
+
+        let index = self.heap_free_list.iter()
+            .position(|&(start, _block_size)| start == block_start)
+            .expect("find_available_heap found a block that is no longer in the free list");
+
+        let (start, block_size) = self.heap_free_list[index];
+        let remaining = block_size - needed;
+
+        // bytes of slack too small to host another header plus at
+        // least one byte of data are folded into this allocation
+        // instead of being silently dropped from the free list forever
+        let recorded_size = if remaining > HEAP_HEADER_SIZE {
+            // shrink the free block so it starts after the new allocation
+            self.heap_free_list[index] = (start + needed, remaining);
+            size
+        } else {
+            self.heap_free_list.remove(index);
+            size + remaining as u32
+        };
+
+        // record the allocation's length in the header so free_heap
+        // knows how many bytes to reclaim later
+        self.write_heap_header(start, recorded_size as u16);
+
+        self.heap_allocations.insert(start);
+
+        return Some(start + HEAP_HEADER_SIZE);
+    }
+
+    fn free_heap(&mut self, position : usize) -> Option<()> {
+        let block_start = position.checked_sub(HEAP_HEADER_SIZE)?;
+
+        // only trust a position that is exactly the start of an
+        // allocation we handed out and haven't already freed; this
+        // also rules out a position whose preceding bytes are
+        // ordinary heap data rather than a real header
+        if !self.heap_allocations.remove(&block_start) {
+            return None;
+        }
+
+        let size = self.read_heap_header(block_start) as usize;
+        let block_size = size.checked_add(HEAP_HEADER_SIZE)?;
+
+        if block_start + block_size > self.raw_memory.len() {
+            return None;
+        }
+
+        // the freed bytes no longer hold meaningful data
+        self.mark_uninitialized(block_start..block_start + block_size);
+
+        // insert the freed block back into the free list, sorted by
+        // start address, then coalesce with any adjacent neighbours
+        let index = self.heap_free_list.iter()
+            .position(|&(start, _size)| start > block_start)
+            .unwrap_or(self.heap_free_list.len());
+
+        self.heap_free_list.insert(index, (block_start, block_size));
+
+        // coalesce with the following block, if adjacent
+        if index + 1 < self.heap_free_list.len() {
+            let (next_start, next_size) = self.heap_free_list[index + 1];
+            let (start, size) = self.heap_free_list[index];
+            if start + size == next_start {
+                self.heap_free_list[index] = (start, size + next_size);
+                self.heap_free_list.remove(index + 1);
+            }
+        }
+
+        // coalesce with the preceding block, if adjacent
+        if index > 0 {
+            let (prev_start, prev_size) = self.heap_free_list[index - 1];
+            let (start, size) = self.heap_free_list[index];
+            if prev_start + prev_size == start {
+                self.heap_free_list[index - 1] = (prev_start, prev_size + size);
+                self.heap_free_list.remove(index);
+            }
+        }
+
+        return Some(());
+    }
+} // impl ManageHeap for Memory
+
+
 impl ManageRegisters for Memory {
     fn get_register_address(&self, register: Register) -> usize {
         return self.register_lookup_table[&(register as i32)] as usize; 
     }
 
-    // ToDo: implement 4 byte registers
     fn get_register_size(register: Register) -> MemoryOperationSize {
         let val : i8 = register as i8;
 
@@ -164,9 +608,148 @@ impl ManageRegisters for Memory {
             return MemoryOperationSize::Byte;
         } else if val >= 8 && val <= 11 {
             return MemoryOperationSize::Word;
+        } else if val >= 12 && val <= 17 {
+            return MemoryOperationSize::Dword;
         }
 
         // if none of the above, return byte
         return MemoryOperationSize::Byte;
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fresh_memory_reads_registers_without_faulting() {
+        let memory = Memory::new();
+
+        assert_eq!(memory.read(0, MemoryOperationSize::Dword), Ok(0));
+    }
+
+    #[test]
+    fn paged_write_crossing_into_an_unmapped_page_faults() {
+        let mut memory = Memory::new();
+        memory.set_addressing_mode(AddressingMode::Paged);
+        memory.map_page(0, 0, PageFlags::READABLE | PageFlags::WRITABLE);
+        // page 1 (256..512) is left unmapped
+
+        let result = memory.write32(254, 0x11223344);
+
+        assert_eq!(result, Err(MemoryFault {
+            address: 254,
+            size: MemoryOperationSize::Dword,
+            kind: MemoryFaultKind::PageFault,
+        }));
+    }
+
+    #[test]
+    fn paged_access_within_a_single_page_succeeds() {
+        let mut memory = Memory::new();
+        memory.set_addressing_mode(AddressingMode::Paged);
+        memory.map_page(0, 0, PageFlags::READABLE | PageFlags::WRITABLE);
+
+        assert!(memory.write32(4, 0x11223344).is_ok());
+        assert_eq!(memory.read32(4), Ok(0x11223344));
+    }
+
+    #[test]
+    fn word_round_trip_preserves_a_high_bit_low_byte() {
+        // the low byte of 255 has bit 7 set; a read16 that sign-extends
+        // it instead of treating it as a plain byte would come back
+        // as -1 (0xFFFF) rather than 255
+        let mut big_endian = Memory::new();
+        big_endian.write16(HEAP_START, 255).unwrap();
+        assert_eq!(big_endian.read16(HEAP_START), Ok(255));
+
+        let mut little_endian = Memory::with_endianness(Endianness::Little);
+        little_endian.write16(HEAP_START, 255).unwrap();
+        assert_eq!(little_endian.read16(HEAP_START), Ok(255));
+    }
+
+    #[test]
+    fn heap_allocate_splits_the_free_block() {
+        let mut memory = Memory::new();
+
+        let first = memory.allocate_heap(16).expect("16 bytes should fit");
+        let second = memory.allocate_heap(16).expect("16 bytes should fit");
+
+        // the second allocation starts after the first's data and header
+        assert_eq!(second, first + 16 + HEAP_HEADER_SIZE);
+    }
+
+    #[test]
+    fn heap_free_coalesces_adjacent_blocks() {
+        let mut memory = Memory::new();
+
+        let first = memory.allocate_heap(16).unwrap();
+        let second = memory.allocate_heap(16).unwrap();
+        let third = memory.allocate_heap(16).unwrap();
+
+        memory.free_heap(first);
+        memory.free_heap(third);
+        memory.free_heap(second);
+
+        // freeing all three back to back should coalesce them into
+        // the single free block the heap started with
+        assert_eq!(memory.heap_free_list, vec![(HEAP_START, memory.raw_memory.len() - HEAP_START)]);
+    }
+
+    #[test]
+    fn heap_allocation_larger_than_the_maximum_allocation_size_is_rejected() {
+        let mut memory = Memory::new();
+
+        assert_eq!(memory.allocate_heap(HEAP_MAX_ALLOCATION_SIZE + 1), None);
+    }
+
+    #[test]
+    fn free_heap_on_a_too_small_position_does_not_panic() {
+        let mut memory = Memory::new();
+
+        assert_eq!(memory.free_heap(0), None);
+    }
+
+    #[test]
+    fn free_heap_on_a_bogus_position_does_not_panic() {
+        let mut memory = Memory::new();
+        memory.allocate_heap(16).unwrap();
+
+        // not the start of any allocation handed out by allocate_heap
+        assert_eq!(memory.free_heap(HEAP_START + HEAP_HEADER_SIZE + 1), None);
+    }
+
+    #[test]
+    fn heap_allocate_then_free_round_trips_a_high_bit_low_byte_size() {
+        // 200 and 255 both have their low byte's high bit set; a
+        // header read back by sign-extending its low byte
+        // would reclaim the wrong number of bytes,
+        // fail to find the allocation at all, or overflow computing
+        // block_size
+        let mut memory = Memory::new();
+
+        let first = memory.allocate_heap(200).expect("200 bytes should fit");
+        assert_eq!(memory.free_heap(first), Some(()));
+
+        let second = memory.allocate_heap(255).expect("255 bytes should fit");
+        assert_eq!(memory.free_heap(second), Some(()));
+
+        // both allocations were freed back to the heap's only block
+        assert_eq!(memory.heap_free_list, vec![(HEAP_START, memory.raw_memory.len() - HEAP_START)]);
+    }
+
+    #[test]
+    fn double_free_is_rejected_and_does_not_alias_allocations() {
+        let mut memory = Memory::new();
+
+        let first = memory.allocate_heap(16).unwrap();
+
+        assert_eq!(memory.free_heap(first), Some(()));
+        assert_eq!(memory.free_heap(first), None);
+
+        let second = memory.allocate_heap(16).unwrap();
+        let third = memory.allocate_heap(16).unwrap();
+
+        assert_ne!(second, third);
+    }
 }
\ No newline at end of file
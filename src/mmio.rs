@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use crate::types::MemoryOperationSize;
+
+/**
+ * MemoryDevice
+ *
+ * Implemented by peripherals that sit behind an MMIO region
+ * instead of raw_memory, e.g. a timer or a console. `offset` is
+ * relative to the start of the region the device was mapped at.
+ */
+pub trait MemoryDevice {
+    fn read(&self, offset: usize, size: MemoryOperationSize) -> i32;
+    fn write(&mut self, offset: usize, size: MemoryOperationSize, value: i32);
+
+    // advances the device by one VM tick; most devices ignore this
+    fn tick(&mut self) {}
+}
+
+struct MmioRegion {
+    end: usize,
+    device: Box<dyn MemoryDevice>,
+}
+
+// number of bytes an access of `size` covers
+fn byte_len(size: MemoryOperationSize) -> usize {
+    match size {
+        MemoryOperationSize::Byte => 1,
+        MemoryOperationSize::Word => 2,
+        MemoryOperationSize::Dword => 4,
+    }
+}
+
+/**
+ * MmioBus
+ *
+ * Maps `[start, end)` ranges of the address space to
+ * MemoryDevice callbacks, keyed by start address in a BTreeMap
+ * so a lookup is a single range query: find the region starting
+ * at or before the address, then check it still covers it.
+ */
+pub struct MmioBus {
+    regions: BTreeMap<usize, MmioRegion>,
+}
+
+impl MmioBus {
+    pub fn new() -> MmioBus {
+        MmioBus {
+            regions: BTreeMap::new(),
+        }
+    }
+
+    // registers a device for `[start, end)`
+    pub fn map(&mut self, start: usize, end: usize, device: Box<dyn MemoryDevice>) {
+        self.regions.insert(start, MmioRegion { end, device });
+    }
+
+    // removes the device mapped at `start`, if any
+    pub fn unmap(&mut self, start: usize) {
+        self.regions.remove(&start);
+    }
+
+    // finds the region covering the whole `[address, address + len)`
+    // range; a region only partially covering the access (or not
+    // covering it at all) does not match, so the access falls
+    // through instead of being serviced out of the wrong bytes
+    fn region_containing(&self, address: usize, len: usize) -> Option<(&usize, &MmioRegion)> {
+        let (start, region) = self.regions.range(..=address).next_back()?;
+
+        if address + len <= region.end {
+            return Some((start, region));
+        }
+
+        return None;
+    }
+
+    // reads through the device mapped over `address`, if any
+    pub fn read(&self, address: usize, size: MemoryOperationSize) -> Option<i32> {
+        self.region_containing(address, byte_len(size)).map(|(start, region)| region.device.read(address - start, size))
+    }
+
+    // writes through the device mapped over `address`; returns
+    // false if no device is mapped there
+    pub fn write(&mut self, address: usize, value: i32, size: MemoryOperationSize) -> bool {
+        let len = byte_len(size);
+
+        let (start, region) = match self.regions.range_mut(..=address).next_back() {
+            Some((start, region)) if address + len <= region.end => (*start, region),
+            _ => return false,
+        };
+
+        region.device.write(address - start, size, value);
+        return true;
+    }
+
+    // advances every mapped device by one VM tick
+    pub fn tick(&mut self) {
+        for region in self.regions.values_mut() {
+            region.device.tick();
+        }
+    }
+}
+
+/**
+ * TimerDevice
+ *
+ * A free-running counter that increments on every VM tick and
+ * wraps around on overflow. Reading it (at any offset/size in
+ * its region) returns the current count; writes are ignored.
+ */
+pub struct TimerDevice {
+    counter: u32,
+}
+
+impl TimerDevice {
+    pub fn new() -> TimerDevice {
+        TimerDevice { counter: 0 }
+    }
+}
+
+impl MemoryDevice for TimerDevice {
+    fn read(&self, _offset: usize, _size: MemoryOperationSize) -> i32 {
+        self.counter as i32
+    }
+
+    fn write(&mut self, _offset: usize, _size: MemoryOperationSize, _value: i32) {
+        // read-only: writes to the timer register are ignored
+    }
+
+    fn tick(&mut self) {
+        self.counter = self.counter.wrapping_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_spilling_past_a_region_end_falls_through() {
+        let mut bus = MmioBus::new();
+        bus.map(200, 201, Box::new(TimerDevice::new()));
+
+        assert_eq!(bus.read(200, MemoryOperationSize::Dword), None);
+    }
+
+    #[test]
+    fn access_fully_inside_a_region_is_serviced() {
+        let mut bus = MmioBus::new();
+        bus.map(200, 204, Box::new(TimerDevice::new()));
+
+        assert_eq!(bus.read(200, MemoryOperationSize::Dword), Some(0));
+    }
+}
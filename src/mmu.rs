@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use crate::memory::{MemoryFault, MemoryFaultKind};
+use crate::types::MemoryOperationSize;
+
+// number of bytes covered by a single page
+pub const PAGE_SIZE: usize = 256;
+
+// permission bits carried by a page table entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageFlags(u8);
+
+impl PageFlags {
+    pub const READABLE:   PageFlags = PageFlags(1 << 0);
+    pub const WRITABLE:   PageFlags = PageFlags(1 << 1);
+    pub const EXECUTABLE: PageFlags = PageFlags(1 << 2);
+    pub const VALID:      PageFlags = PageFlags(1 << 3);
+    pub const USER:       PageFlags = PageFlags(1 << 4);
+
+    pub fn contains(&self, other: PageFlags) -> bool {
+        (self.0 & other.0) == other.0
+    }
+}
+
+impl std::ops::BitOr for PageFlags {
+    type Output = PageFlags;
+
+    fn bitor(self, rhs: PageFlags) -> PageFlags {
+        PageFlags(self.0 | rhs.0)
+    }
+}
+
+// whether an access is reading data, writing data, or fetching
+// an instruction, so page permissions can be checked accordingly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    Fetch,
+}
+
+// whether Memory translates addresses through the Mmu or treats
+// them as physical offsets directly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    Bare,
+    Paged,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PageTableEntry {
+    physical_page: usize,
+    flags: PageFlags,
+}
+
+/**
+ * Mmu
+ *
+ * Translates virtual addresses to physical offsets in
+ * Memory's raw_memory array through a page table, enforcing
+ * each page's read/write/execute permissions.
+ */
+pub struct Mmu {
+    page_table: HashMap<usize, PageTableEntry>,
+}
+
+impl Mmu {
+    pub fn new() -> Mmu {
+        Mmu {
+            page_table: HashMap::new(),
+        }
+    }
+
+    // maps the page containing `vaddr` to the page containing
+    // `paddr`, with the given permission flags
+    pub fn map_page(&mut self, vaddr: usize, paddr: usize, flags: PageFlags) {
+        self.page_table.insert(vaddr / PAGE_SIZE, PageTableEntry {
+            physical_page: paddr / PAGE_SIZE,
+            flags: flags | PageFlags::VALID,
+        });
+    }
+
+    // removes the mapping for the page containing `vaddr`
+    pub fn unmap_page(&mut self, vaddr: usize) {
+        self.page_table.remove(&(vaddr / PAGE_SIZE));
+    }
+
+    // translates a `len`-byte access starting at `vaddr` into a
+    // physical offset. Every page the access touches is checked
+    // for a valid mapping and for the right permission, and the
+    // pages must translate to physically contiguous bytes, so a
+    // value can never be serviced partly from one mapping and
+    // partly from another (or from memory with no mapping at all)
+    pub fn translate(&self, vaddr: usize, len: usize, access: AccessKind, size: MemoryOperationSize) -> Result<usize, MemoryFault> {
+        let required = match access {
+            AccessKind::Read => PageFlags::READABLE,
+            AccessKind::Write => PageFlags::WRITABLE,
+            AccessKind::Fetch => PageFlags::EXECUTABLE,
+        };
+
+        let mut physical_start = None;
+
+        for offset in 0..len {
+            let byte_addr = vaddr + offset;
+
+            let entry = match self.page_table.get(&(byte_addr / PAGE_SIZE)) {
+                Some(entry) if entry.flags.contains(PageFlags::VALID) => entry,
+                _ => return Err(MemoryFault {
+                    address: vaddr,
+                    size: size,
+                    kind: MemoryFaultKind::PageFault,
+                }),
+            };
+
+            if !entry.flags.contains(required) {
+                return Err(MemoryFault {
+                    address: vaddr,
+                    size: size,
+                    kind: MemoryFaultKind::ProtectionViolation,
+                });
+            }
+
+            let physical = entry.physical_page * PAGE_SIZE + (byte_addr % PAGE_SIZE);
+
+            match physical_start {
+                None => physical_start = Some(physical),
+                Some(start) if physical != start + offset => return Err(MemoryFault {
+                    address: vaddr,
+                    size: size,
+                    kind: MemoryFaultKind::PageFault,
+                }),
+                _ => {},
+            }
+        }
+
+        return Ok(physical_start.unwrap());
+    }
+}
@@ -0,0 +1,39 @@
+// shared primitive types used across the register file and
+// the memory subsystem
+
+// how many bytes a memory access or register covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryOperationSize {
+    Byte,
+    Word,
+    Dword,
+}
+
+// the CPU's general purpose registers, ordered so that the
+// 8-bit halves come first, then the 16-bit registers, then the
+// 32-bit registers (see ManageRegisters::get_register_size)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    AL,
+    BL,
+    CL,
+    DL,
+    AH,
+    BH,
+    CH,
+    DH,
+
+    AX,
+    BX,
+    CX,
+    DX,
+
+    EAX,
+    EBX,
+    ECX,
+    EDX,
+    ESP,
+    EBP,
+
+    Unknwown,
+}